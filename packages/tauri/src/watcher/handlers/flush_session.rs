@@ -1,4 +1,7 @@
-use std::sync::{Arc, Mutex, TryLockError};
+use std::{
+    sync::{Arc, Mutex, TryLockError},
+    time::Duration,
+};
 
 use anyhow::{Context, Result};
 use tauri::{AppHandle, Manager};
@@ -10,6 +13,10 @@ use crate::{
 
 use super::events;
 
+// idle repositories are evicted after this long without a flush, so a project that goes quiet
+// doesn't hold an open git2::Repository handle forever.
+const PROJECT_REPOSITORY_CACHE_TTI: Duration = Duration::from_secs(120);
+
 #[derive(Clone)]
 pub struct Handler {
     inner: Arc<Mutex<HandlerInner>>,
@@ -38,6 +45,18 @@ impl Handler {
             Err(TryLockError::WouldBlock) => Ok(vec![]),
         }
     }
+
+    /// Evicts `project_id`'s cached `project_repository::Repository`, so the next flush after a
+    /// base-branch reset/update re-opens it instead of reusing a handle that may now point at
+    /// stale base-branch state. Waits for a flush in progress to finish before evicting.
+    pub fn invalidate_project_repository_cache(&self, project_id: &ProjectId) -> Result<()> {
+        let inner = self
+            .inner
+            .lock()
+            .map_err(|_| anyhow::anyhow!("mutex poisoned"))?;
+        inner.project_repositories.invalidate(project_id);
+        Ok(())
+    }
 }
 
 struct HandlerInner {
@@ -45,6 +64,9 @@ struct HandlerInner {
     project_store: projects::Controller,
     vbrach_controller: virtual_branches::Controller,
     users: users::Controller,
+    // memoizes opened project repositories across flushes, since rapid delta activity otherwise
+    // re-opens the same repository on every single session flush.
+    project_repositories: moka::sync::Cache<ProjectId, Arc<project_repository::Repository>>,
 }
 
 impl TryFrom<&AppHandle> for HandlerInner {
@@ -59,11 +81,28 @@ impl TryFrom<&AppHandle> for HandlerInner {
                 .inner()
                 .clone(),
             users: users::Controller::from(value),
+            project_repositories: moka::sync::Cache::builder()
+                .time_to_idle(PROJECT_REPOSITORY_CACHE_TTI)
+                .build(),
         })
     }
 }
 
 impl HandlerInner {
+    fn open_project_repository(
+        &self,
+        project_id: &ProjectId,
+        project: &projects::Project,
+    ) -> Result<Arc<project_repository::Repository>> {
+        self.project_repositories
+            .try_get_with(*project_id, || {
+                project_repository::Repository::open(project)
+                    .context("failed to open repository")
+                    .map(Arc::new)
+            })
+            .map_err(|error| anyhow::anyhow!("{error}"))
+    }
+
     pub fn handle(
         &self,
         project_id: &ProjectId,
@@ -75,8 +114,7 @@ impl HandlerInner {
             .context("failed to get project")?;
 
         let user = self.users.get_user()?;
-        let project_repository =
-            project_repository::Repository::open(&project).context("failed to open repository")?;
+        let project_repository = self.open_project_repository(project_id, &project)?;
         let gb_repo = gb_repository::Repository::open(
             &self.local_data_dir,
             &project_repository,
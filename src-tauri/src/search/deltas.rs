@@ -1,7 +1,18 @@
+use super::highlight::{self, HighlightedSpan};
 use crate::{deltas, projects, sessions};
 use anyhow::Result;
-use std::{fs, path::Path};
-use tantivy::{collector, directory::MmapDirectory, schema};
+use std::{collections::HashSet, fs, path::Path};
+use tantivy::{
+    collector,
+    directory::MmapDirectory,
+    query::{BooleanQuery, Occur, Query, RegexQuery, TermQuery},
+    schema::{self, IndexRecordOption},
+    SnippetGenerator, Term,
+};
+
+// bump whenever `schema()` changes incompatibly, so a pre-existing on-disk index built under
+// an older schema is left alone instead of tripping `Index::open_or_create` on the new one.
+const SCHEMA_VERSION: u32 = 3;
 
 #[derive(Clone)]
 pub struct DeltasIndex {
@@ -13,7 +24,8 @@ fn schema() -> schema::Schema {
     let mut schema_builder = schema::Schema::builder();
     schema_builder.add_text_field(
         "session_hash",
-        schema::STORED, // store the value so we can retrieve it from search results
+        schema::STRING // exact, untokenized match so we can delete-by-term on re-index/gc
+        | schema::STORED, // store the value so we can retrieve it from search results
     );
     schema_builder.add_u64_field(
         "index",
@@ -25,27 +37,61 @@ fn schema() -> schema::Schema {
         | schema::STORED // store the value so we can retrieve it from search results
         | schema::FAST, // makes the field faster to filter / sort on
     );
+    schema_builder.add_text_field(
+        "file_path_raw",
+        schema::STRING, // untokenized copy of file_path, so prefix/glob filters match the
+                         // whole path instead of a single token of it
+    );
     schema_builder.add_text_field(
         "diff",
         schema::TEXT, // we want to search on this field, tokenize and index it
     );
     schema_builder.add_bool_field(
         "is_addition",
-        schema::FAST, // we want to filter on the field
+        schema::INDEXED | schema::FAST, // indexed so we can filter on it, fast so we can sort on it
     );
-    schema_builder.add_u64_field(
+    schema_builder.add_bool_field(
         "is_deletion",
-        schema::FAST, // we want to filter on the field
+        schema::INDEXED | schema::FAST, // indexed so we can filter on it, fast so we can sort on it
     );
     schema_builder.build()
 }
 
 const WRITE_BUFFER_SIZE: usize = 10_000_000; // 10MB
 
+#[derive(Debug, Default, Clone)]
+pub struct SearchQuery {
+    pub q: String,
+    // only return deltas that added text
+    pub only_additions: bool,
+    // only return deltas that removed text
+    pub only_deletions: bool,
+    // only return deltas from files whose path starts with this prefix
+    pub file_path_prefix: Option<String>,
+    // syntax-highlight each result's fragment by file_path's extension; off by default since
+    // callers that only need plain text (e.g. a count) shouldn't pay the parsing cost.
+    pub highlight: bool,
+}
+
+impl From<&str> for SearchQuery {
+    fn from(q: &str) -> Self {
+        Self {
+            q: q.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
 pub struct SearchResult {
     pub session_hash: String,
     pub file_path: String,
     pub index: u64,
+    // the matched excerpt of the diff, as plain text; see `highlighted` for the matched terms
+    pub fragment: String,
+    // byte ranges into `fragment` that should be rendered as highlighted
+    pub highlighted: Vec<(usize, usize)>,
+    // present only when the query asked for syntax highlighting
+    pub highlighted_syntax: Option<Vec<HighlightedSpan>>,
 }
 
 impl DeltasIndex {
@@ -57,7 +103,7 @@ impl DeltasIndex {
             .as_ref()
             .join("indexes")
             .join(&project.id)
-            .join("deltas");
+            .join(format!("deltas-v{}", SCHEMA_VERSION));
         fs::create_dir_all(&dir)?;
 
         let schema = schema();
@@ -100,11 +146,17 @@ impl DeltasIndex {
             Some(hash) => self.with_writer(|writer| {
                 let field_session_hash = self.index.schema().get_field("session_hash").unwrap();
                 let field_file_path = self.index.schema().get_field("file_path").unwrap();
+                let field_file_path_raw = self.index.schema().get_field("file_path_raw").unwrap();
                 let field_diff = self.index.schema().get_field("diff").unwrap();
                 let field_is_addition = self.index.schema().get_field("is_addition").unwrap();
                 let field_is_deletion = self.index.schema().get_field("is_deletion").unwrap();
                 let field_index = self.index.schema().get_field("index").unwrap();
 
+                // re-indexing a session (e.g. after it's re-flushed) must replace its documents
+                // rather than duplicate them, so drop anything already indexed under this hash
+                // before adding the fresh set. delete + add commit atomically together.
+                writer.delete_term(Term::from_field_text(field_session_hash, hash));
+
                 // index every file
                 for (file_path, deltas) in deltas.into_iter() {
                     // keep the state of the file after each delta operation
@@ -123,6 +175,7 @@ impl DeltasIndex {
                             doc.add_u64(field_index, i.try_into()?);
                             doc.add_text(field_session_hash, hash);
                             doc.add_text(field_file_path, file_path.as_str());
+                            doc.add_text(field_file_path_raw, file_path.as_str());
                             match operation {
                                 deltas::Operation::Delete((from, len)) => {
                                     // here we use the file_text to calculate the diff
@@ -151,20 +204,98 @@ impl DeltasIndex {
         }
     }
 
-    pub fn search(&self, q: &str) -> Result<Vec<SearchResult>> {
+    // deletes every document belonging to a single session.
+    pub fn delete_session(&self, session_hash: &str) -> Result<()> {
+        self.with_writer(|writer| {
+            let field_session_hash = self.index.schema().get_field("session_hash").unwrap();
+            writer.delete_term(Term::from_field_text(field_session_hash, session_hash));
+            Ok(())
+        })
+    }
+
+    // deletes every document whose session_hash is not in `live_hashes`, so the index stays in
+    // sync once sessions are pruned elsewhere.
+    pub fn garbage_collect(&self, live_hashes: &HashSet<String>) -> Result<()> {
+        let field_session_hash = self.index.schema().get_field("session_hash").unwrap();
+
+        self.reader.reload()?;
+        let searcher = self.reader.searcher();
+        let doc_addresses =
+            searcher.search(&tantivy::query::AllQuery, &collector::DocSetCollector)?;
+        let mut stale_hashes = HashSet::new();
+        for doc_address in doc_addresses {
+            let doc = searcher.doc(doc_address)?;
+            if let Some(hash) = doc.get_first(field_session_hash).and_then(|v| v.as_text()) {
+                if !live_hashes.contains(hash) {
+                    stale_hashes.insert(hash.to_string());
+                }
+            }
+        }
+
+        if stale_hashes.is_empty() {
+            return Ok(());
+        }
+
+        self.with_writer(|writer| {
+            for hash in &stale_hashes {
+                writer.delete_term(Term::from_field_text(field_session_hash, hash));
+            }
+            Ok(())
+        })
+    }
+
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchResult>> {
         let field_file_path = self.index.schema().get_field("file_path").unwrap();
+        let field_file_path_raw = self.index.schema().get_field("file_path_raw").unwrap();
         let field_diff = self.index.schema().get_field("diff").unwrap();
         let field_session_hash = self.index.schema().get_field("session_hash").unwrap();
         let field_index = self.index.schema().get_field("index").unwrap();
+        let field_is_addition = self.index.schema().get_field("is_addition").unwrap();
+        let field_is_deletion = self.index.schema().get_field("is_deletion").unwrap();
 
         let query_parser =
             &tantivy::query::QueryParser::for_index(&self.index, vec![field_file_path, field_diff]);
 
-        let query = query_parser.parse_query(q)?;
+        let parsed_query = query_parser.parse_query(&query.q)?;
+
+        // AND the parsed query together with whatever facet/flag filters were requested
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, parsed_query)];
+        if query.only_additions {
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_bool(field_is_addition, true),
+                    IndexRecordOption::Basic,
+                )),
+            ));
+        }
+        if query.only_deletions {
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_bool(field_is_deletion, true),
+                    IndexRecordOption::Basic,
+                )),
+            ));
+        }
+        if let Some(file_path_prefix) = &query.file_path_prefix {
+            // match against the untokenized `file_path_raw` field: `file_path` is tokenized,
+            // so no single indexed term contains the whole path and a multi-segment prefix
+            // like "src/components" could never match any of its terms.
+            let pattern = format!("{}.*", escape_regex(file_path_prefix));
+            clauses.push((
+                Occur::Must,
+                Box::new(RegexQuery::from_pattern(&pattern, field_file_path_raw)?),
+            ));
+        }
+        let boolean_query = BooleanQuery::new(clauses);
 
         self.reader.reload()?;
         let searcher = self.reader.searcher();
-        let top_docs = searcher.search(&query, &collector::TopDocs::with_limit(10))?;
+        let top_docs = searcher.search(&boolean_query, &collector::TopDocs::with_limit(10))?;
+
+        // build highlighted excerpts of the diff field for each hit
+        let snippet_generator = SnippetGenerator::create(&searcher, &boolean_query, field_diff)?;
 
         let results = top_docs
             .iter()
@@ -185,10 +316,25 @@ impl DeltasIndex {
                     .unwrap()
                     .as_u64()
                     .unwrap();
+
+                let snippet = snippet_generator.snippet_from_doc(&retrieved_doc);
+                let highlighted = snippet
+                    .highlighted()
+                    .iter()
+                    .map(|range| (range.start, range.end))
+                    .collect();
+                let fragment = snippet.fragment().to_string();
+                let highlighted_syntax = query
+                    .highlight
+                    .then(|| highlight::highlight(file_path, &fragment));
+
                 Ok(SearchResult {
                     file_path: file_path.to_string(),
                     session_hash: session_hash.to_string(),
                     index,
+                    fragment,
+                    highlighted,
+                    highlighted_syntax,
                 })
             })
             .collect::<Result<Vec<SearchResult>>>()?;
@@ -196,3 +342,28 @@ impl DeltasIndex {
         Ok(results)
     }
 }
+
+// escapes regex metacharacters so a user-supplied file path prefix is matched literally.
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape_regex;
+
+    #[test]
+    fn escape_regex_escapes_metacharacters() {
+        assert_eq!(escape_regex("src/components"), "src/components");
+        assert_eq!(escape_regex("a.b+c"), "a\\.b\\+c");
+        assert_eq!(escape_regex("(foo|bar)"), "\\(foo\\|bar\\)");
+        assert_eq!(escape_regex("a[b]c"), "a\\[b\\]c");
+    }
+}
@@ -0,0 +1,101 @@
+use std::{path::Path, sync::OnceLock};
+
+use serde::Serialize;
+use syntect::{
+    parsing::{ParseState, ScopeStack, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HighlightedSpan {
+    // innermost syntect scope covering this run (e.g. "keyword.control.rs"), used by the front
+    // end as a CSS class rather than us baking in any particular color scheme.
+    pub class: String,
+    pub text: String,
+}
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// Tokenizes `text` line-by-line using the syntax inferred from `file_path`'s extension, and
+/// emits `(scope, text)` runs instead of raw ANSI so the caller can render them however it likes.
+/// Falls back to plain text for unrecognized extensions rather than erroring.
+pub fn highlight(file_path: &str, text: &str) -> Vec<HighlightedSpan> {
+    let syntax_set = syntax_set();
+    let syntax = Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|ext| syntax_set.find_syntax_by_extension(ext))
+        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+
+    let mut parse_state = ParseState::new(syntax);
+    let mut scope_stack = ScopeStack::new();
+    let mut spans = Vec::new();
+
+    for line in LinesWithEndings::from(text) {
+        let Ok(ops) = parse_state.parse_line(line, syntax_set) else {
+            spans.push(HighlightedSpan {
+                class: current_class(&scope_stack),
+                text: line.to_string(),
+            });
+            continue;
+        };
+
+        let mut last = 0;
+        for (offset, op) in &ops {
+            if *offset > last {
+                spans.push(HighlightedSpan {
+                    class: current_class(&scope_stack),
+                    text: line[last..*offset].to_string(),
+                });
+                last = *offset;
+            }
+            let _ = scope_stack.apply(op);
+        }
+        if last < line.len() {
+            spans.push(HighlightedSpan {
+                class: current_class(&scope_stack),
+                text: line[last..].to_string(),
+            });
+        }
+    }
+
+    spans
+}
+
+fn current_class(scope_stack: &ScopeStack) -> String {
+    scope_stack
+        .as_slice()
+        .last()
+        .map(|scope| scope.to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::highlight;
+
+    #[test]
+    fn concatenating_spans_recovers_the_original_text() {
+        let text = "fn main() {\n    let x = 1;\n}\n";
+        let spans = highlight("main.rs", text);
+        let recovered: String = spans.iter().map(|span| span.text.as_str()).collect();
+        assert_eq!(recovered, text);
+    }
+
+    #[test]
+    fn unknown_extension_falls_back_to_plain_text_without_panicking() {
+        let text = "some unrecognized content\n";
+        let spans = highlight("file.some-unknown-ext", text);
+        let recovered: String = spans.iter().map(|span| span.text.as_str()).collect();
+        assert_eq!(recovered, text);
+    }
+
+    #[test]
+    fn empty_input_produces_no_spans() {
+        assert!(highlight("main.rs", "").is_empty());
+    }
+}
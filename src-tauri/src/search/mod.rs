@@ -0,0 +1,4 @@
+pub mod deltas;
+pub mod highlight;
+
+pub use deltas::DeltasIndex;
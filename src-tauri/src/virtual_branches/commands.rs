@@ -1,9 +1,10 @@
 use tauri::{AppHandle, Manager};
 use timed::timed;
 
-use crate::{error::Error, project_repository::branch};
+use crate::{error::Error, project_repository::branch, watcher::handlers::flush_session};
 
 use super::controller::Controller;
+use super::status::StatusUpdate;
 
 #[timed(duration(printer = "debug!"))]
 #[tauri::command(async)]
@@ -25,10 +26,11 @@ pub async fn commit_virtual_branch(
 pub async fn list_virtual_branches(
     handle: AppHandle,
     project_id: &str,
+    highlight: Option<bool>,
 ) -> Result<Vec<super::VirtualBranch>, Error> {
     handle
         .state::<Controller>()
-        .list_virtual_branches(project_id)
+        .list_virtual_branches(project_id, highlight)
         .await
         .map_err(Into::into)
 }
@@ -81,11 +83,13 @@ pub async fn set_base_branch(
     project_id: &str,
     branch: &str,
 ) -> Result<super::BaseBranch, Error> {
-    handle
+    let base_branch = handle
         .state::<Controller>()
         .set_base_branch(project_id, branch)
         .await
-        .map_err(Into::into)
+        .map_err(Into::into)?;
+    invalidate_project_repository_cache(&handle, project_id)?;
+    Ok(base_branch)
 }
 
 #[timed(duration(printer = "debug!"))]
@@ -95,6 +99,19 @@ pub async fn update_base_branch(handle: AppHandle, project_id: &str) -> Result<(
         .state::<Controller>()
         .update_base_branch(project_id)
         .await
+        .map_err(Into::into)?;
+    invalidate_project_repository_cache(&handle, project_id)
+}
+
+// a base-branch reset or update can point the project at a different commit/path, so the
+// session-flush handler's cached project_repository::Repository must not be handed out stale.
+fn invalidate_project_repository_cache(handle: &AppHandle, project_id: &str) -> Result<(), Error> {
+    let project_id = project_id
+        .parse()
+        .map_err(|_| Error::from(anyhow::anyhow!("invalid project id: {project_id}")))?;
+    handle
+        .state::<flush_session::Handler>()
+        .invalidate_project_repository_cache(&project_id)
         .map_err(Into::into)
 }
 
@@ -162,4 +179,33 @@ pub async fn push_virtual_branch(
         .push_virtual_branch(project_id, branch_id)
         .await
         .map_err(Into::into)
+}
+
+#[timed(duration(printer = "debug!"))]
+#[tauri::command(async)]
+pub async fn format_patch_virtual_branch(
+    handle: AppHandle,
+    project_id: &str,
+    branch_name: &str,
+    base_branch_name: &str,
+) -> Result<String, Error> {
+    handle
+        .state::<Controller>()
+        .format_patch_virtual_branch(project_id, branch_name, base_branch_name)
+        .await
+        .map_err(Into::into)
+}
+
+#[timed(duration(printer = "debug!"))]
+#[tauri::command(async)]
+pub async fn list_virtual_branch_status(
+    handle: AppHandle,
+    project_id: &str,
+    since_scan_id: Option<u64>,
+) -> Result<StatusUpdate, Error> {
+    handle
+        .state::<Controller>()
+        .list_virtual_branch_status(project_id, since_scan_id)
+        .await
+        .map_err(Into::into)
 }
\ No newline at end of file
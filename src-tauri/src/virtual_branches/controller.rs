@@ -0,0 +1,351 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use git2::{Email, EmailCreateOptions};
+
+use super::status::{ScanSnapshot, StatusUpdate};
+use super::{Hunk, VirtualBranch, VirtualBranchFile};
+use crate::{projects, search::highlight};
+
+const VIRTUAL_BRANCH_REFS_NAMESPACE: &str = "refs/gitbutler/virtual-branches";
+const UNASSIGNED_BRANCH_ID: &str = "unassigned";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ControllerError {
+    #[error(transparent)]
+    VerifyError(#[from] anyhow::Error),
+    #[error("since_scan_id {requested} is stale, last known scan_id is {last:?}")]
+    StaleScanId { requested: u64, last: Option<u64> },
+}
+
+#[derive(Clone)]
+pub struct Controller {
+    projects: projects::Controller,
+    // last full status scan per project, used to compute deltas and to validate a caller's
+    // `since_scan_id` against what we actually last handed out.
+    last_scan: Arc<Mutex<HashMap<String, ScanSnapshot>>>,
+}
+
+impl Controller {
+    pub fn new(projects: projects::Controller) -> Self {
+        Self {
+            projects,
+            last_scan: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Scans the working tree for `project_id`, grouping dirty files under the applied virtual
+    /// branch they belong to. When `since_scan_id` is `Some`, only entries that changed since
+    /// that scan are returned; it must match the `scan_id` of the last scan we handed out for
+    /// this project, or the caller is looking at stale state and we error rather than silently
+    /// returning a wrong delta.
+    pub async fn list_virtual_branch_status(
+        &self,
+        project_id: &str,
+        since_scan_id: Option<u64>,
+    ) -> Result<StatusUpdate, ControllerError> {
+        let project = self
+            .projects
+            .get(project_id)
+            .context("failed to get project")?;
+        let repo = git2::Repository::open(&project.path).context("failed to open repository")?;
+
+        let mut last_scan = self.last_scan.lock().unwrap();
+        let previous = last_scan.get(project_id).cloned();
+        let last_known_scan_id = previous.as_ref().map(|snapshot| snapshot.scan_id);
+
+        if let Some(since_scan_id) = since_scan_id {
+            if Some(since_scan_id) != last_known_scan_id {
+                return Err(ControllerError::StaleScanId {
+                    requested: since_scan_id,
+                    last: last_known_scan_id,
+                });
+            }
+        }
+
+        let next_scan_id = last_known_scan_id.unwrap_or(0) + 1;
+        let current =
+            scan_working_tree(&repo, next_scan_id).context("failed to scan working tree")?;
+        // without a since_scan_id the caller gets the full status, so diff against an empty scan
+        let previous = match since_scan_id {
+            Some(_) => previous.unwrap_or_default(),
+            None => ScanSnapshot::default(),
+        };
+        let (branches, removed_paths) = current.delta_since(&previous);
+
+        last_scan.insert(project_id.to_string(), current.clone());
+
+        Ok(StatusUpdate {
+            scan_id: current.scan_id,
+            branches,
+            removed_paths,
+        })
+    }
+
+    /// Lists the first applied virtual branch (in ref order), crediting to it every file that
+    /// differs between the index and the working tree (untracked files included), with those
+    /// diffs. Other applied branches are not listed, and no branch is returned when none is
+    /// applied. When `highlight` is true, each
+    /// hunk's diff is additionally tokenized via `search::highlight::highlight` so the front end
+    /// can render it without re-parsing; callers that don't need it can skip the cost.
+    pub async fn list_virtual_branches(
+        &self,
+        project_id: &str,
+        highlight_enabled: Option<bool>,
+    ) -> Result<Vec<VirtualBranch>, ControllerError> {
+        let project = self
+            .projects
+            .get(project_id)
+            .context("failed to get project")?;
+        let repo = git2::Repository::open(&project.path).context("failed to open repository")?;
+        let highlight_enabled = highlight_enabled.unwrap_or(false);
+
+        let applied_branch_ids = applied_virtual_branch_ids(&repo)?;
+        let Some(branch_id) = applied_branch_ids.first() else {
+            return Ok(vec![]);
+        };
+
+        let mut diff_options = git2::DiffOptions::new();
+        diff_options.include_untracked(true).recurse_untracked_dirs(true);
+        let diff = repo
+            .diff_index_to_workdir(None, Some(&mut diff_options))
+            .context("failed to diff index to workdir")?;
+
+        let mut files = Vec::new();
+        for delta_index in 0..diff.deltas().count() {
+            let Some(mut patch) =
+                git2::Patch::from_diff(&diff, delta_index).context("failed to build patch")?
+            else {
+                continue;
+            };
+            let Some(path) = patch
+                .delta()
+                .new_file()
+                .path()
+                .and_then(|path| path.to_str())
+            else {
+                continue;
+            };
+
+            let diff_text = String::from_utf8_lossy(&patch.to_buf()?).into_owned();
+            let highlighted_diff =
+                highlight_enabled.then(|| highlight::highlight(path, &diff_text));
+
+            files.push(VirtualBranchFile {
+                path: path.to_string(),
+                hunks: vec![Hunk {
+                    diff: diff_text,
+                    highlighted_diff,
+                }],
+            });
+        }
+
+        Ok(vec![VirtualBranch {
+            id: branch_id.clone(),
+            name: branch_id.clone(),
+            files,
+        }])
+    }
+
+    /// Renders every commit unique to `branch_name` relative to `base_branch_name` as a
+    /// `git format-patch`-style mbox string, oldest commit first.
+    pub async fn format_patch_virtual_branch(
+        &self,
+        project_id: &str,
+        branch_name: &str,
+        base_branch_name: &str,
+    ) -> Result<String, ControllerError> {
+        let project = self
+            .projects
+            .get(project_id)
+            .context("failed to get project")?;
+        let repo = git2::Repository::open(&project.path).context("failed to open repository")?;
+
+        let branch_oid = repo
+            .refname_to_id(branch_name)
+            .context("failed to resolve branch ref")?;
+        let base_oid = repo
+            .refname_to_id(base_branch_name)
+            .context("failed to resolve base branch ref")?;
+
+        Ok(format_patch_series(&repo, Some(base_oid), branch_oid)?)
+    }
+}
+
+/// Runs a single `git status` style scan and attributes every dirty path to an applied virtual
+/// branch. There is no real hunk-ownership tracking in this tree yet, so the attribution is a
+/// best-effort heuristic: if exactly one virtual branch is applied, every dirty path is credited
+/// to it; with zero or multiple applied branches we can't tell which branch a given file belongs
+/// to, so everything falls back to `UNASSIGNED_BRANCH_ID` rather than guessing wrong.
+fn scan_working_tree(repo: &git2::Repository, scan_id: u64) -> Result<ScanSnapshot> {
+    use super::status::FileStatus;
+
+    let applied_branch_ids = applied_virtual_branch_ids(repo)?;
+    let branch_id = match applied_branch_ids.as_slice() {
+        [only] => only.clone(),
+        _ => UNASSIGNED_BRANCH_ID.to_string(),
+    };
+
+    let mut options = git2::StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+    let statuses = repo.statuses(Some(&mut options))?;
+
+    let mut entries = HashMap::new();
+    for entry in statuses.iter() {
+        let Some(repo_path) = entry.path() else {
+            continue;
+        };
+        let flags = entry.status();
+        let status = if flags.is_conflicted() {
+            FileStatus::Conflicted
+        } else if flags.is_wt_modified() || flags.is_index_modified() {
+            FileStatus::Modified
+        } else if flags.is_wt_deleted() || flags.is_index_deleted() {
+            FileStatus::Deleted
+        } else if flags.is_index_new() {
+            FileStatus::Added
+        } else {
+            FileStatus::Untracked
+        };
+        entries.insert(repo_path.to_string(), (branch_id.clone(), status));
+    }
+
+    Ok(ScanSnapshot { scan_id, entries })
+}
+
+/// Lists the ids of virtual branches that are currently applied, i.e. have a ref under
+/// `refs/gitbutler/virtual-branches/{id}`.
+fn applied_virtual_branch_ids(repo: &git2::Repository) -> Result<Vec<String>> {
+    let mut ids = Vec::new();
+    for reference in repo.references_glob(&format!("{VIRTUAL_BRANCH_REFS_NAMESPACE}/*"))? {
+        let reference = reference?;
+        if let Some(name) = reference.name() {
+            if let Some(id) = name.strip_prefix(&format!("{VIRTUAL_BRANCH_REFS_NAMESPACE}/")) {
+                ids.push(id.to_string());
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// Walks the commits reachable from `head_oid` but not from `base_oid`, oldest first, and
+/// concatenates a `git format-patch`-style mbox entry for each one. `base_oid` of `None` walks
+/// all the way down to the root commit.
+fn format_patch_series(
+    repo: &git2::Repository,
+    base_oid: Option<git2::Oid>,
+    head_oid: git2::Oid,
+) -> Result<String> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    revwalk.push(head_oid)?;
+    if let Some(base_oid) = base_oid {
+        revwalk.hide(base_oid)?;
+    }
+
+    let commits = revwalk
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .context("failed to walk commits")?
+        .into_iter()
+        .map(|oid| repo.find_commit(oid))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .context("failed to resolve commit")?;
+
+    let total_patches = commits.len();
+    let mut mbox = String::new();
+
+    for (i, commit) in commits.iter().enumerate() {
+        // a root commit (no parent) is diffed against an empty tree, rather than erroring.
+        let parent_tree = if commit.parent_count() > 0 {
+            Some(commit.parent(0)?.tree()?)
+        } else {
+            None
+        };
+        let commit_tree = commit.tree()?;
+
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit_tree), None)?;
+
+        let author = commit.author();
+        let message = commit.message().unwrap_or_default();
+        let (subject, body) = message.split_once('\n').unwrap_or((message, ""));
+
+        let mut opts = EmailCreateOptions::new();
+        let email = Email::from_diff(
+            &diff,
+            (i + 1).try_into()?,
+            total_patches.try_into()?,
+            &commit.id(),
+            subject,
+            body.trim(),
+            &author,
+            &mut opts,
+        )
+        .context("failed to build patch email")?;
+
+        mbox.push_str(email.as_slice());
+    }
+
+    Ok(mbox)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_patch_series;
+
+    fn init_repo(dir: &std::path::Path) -> git2::Repository {
+        let repo = git2::Repository::init(dir).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        repo
+    }
+
+    fn commit_file(repo: &git2::Repository, dir: &std::path::Path, name: &str, contents: &str) -> git2::Oid {
+        std::fs::write(dir.join(name), contents).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new(name)).unwrap();
+        index.write().unwrap();
+        let tree_id = index.write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let sig = repo.signature().unwrap();
+        let parents = match repo.head().ok().and_then(|h| h.target()) {
+            Some(oid) => vec![repo.find_commit(oid).unwrap()],
+            None => vec![],
+        };
+        let parent_refs = parents.iter().collect::<Vec<_>>();
+        repo.commit(Some("HEAD"), &sig, &sig, "message", &tree, &parent_refs)
+            .unwrap()
+    }
+
+    #[test]
+    fn series_covers_base_to_head_exclusive_of_base() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo = init_repo(tmp.path());
+
+        // root commit becomes the "base" the virtual branch was created from
+        let base_oid = commit_file(&repo, tmp.path(), "a.txt", "a");
+        commit_file(&repo, tmp.path(), "b.txt", "b");
+        let head_oid = commit_file(&repo, tmp.path(), "c.txt", "c");
+
+        // two commits are unique to the branch; the base commit itself must not be re-emitted
+        let mbox = format_patch_series(&repo, Some(base_oid), head_oid).unwrap();
+        assert_eq!(mbox.matches("Subject:").count(), 2);
+    }
+
+    #[test]
+    fn root_commit_diffs_against_empty_tree_not_its_missing_parent() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo = init_repo(tmp.path());
+
+        let root_oid = commit_file(&repo, tmp.path(), "a.txt", "a");
+
+        // walking with no base hides nothing, so the walk reaches the root commit itself, which
+        // has no parent: format_patch_series must diff it against an empty tree rather than
+        // fail on `commit.parent(0)`.
+        let mbox = format_patch_series(&repo, None, root_oid).unwrap();
+        assert_eq!(mbox.matches("Subject:").count(), 1);
+    }
+}
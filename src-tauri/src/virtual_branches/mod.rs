@@ -0,0 +1,32 @@
+pub mod commands;
+pub mod controller;
+pub mod status;
+
+use serde::Serialize;
+
+pub use controller::Controller;
+
+use crate::search::highlight::HighlightedSpan;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hunk {
+    pub diff: String,
+    // present only when the caller asked for syntax highlighting
+    pub highlighted_diff: Option<Vec<HighlightedSpan>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualBranchFile {
+    pub path: String,
+    pub hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualBranch {
+    pub id: String,
+    pub name: String,
+    pub files: Vec<VirtualBranchFile>,
+}
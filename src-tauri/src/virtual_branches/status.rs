@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FileStatus {
+    Added,
+    Modified,
+    Deleted,
+    Conflicted,
+    Untracked,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusEntry {
+    pub repo_path: String,
+    pub status: FileStatus,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BranchStatus {
+    pub branch_id: String,
+    pub entries: Vec<StatusEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusUpdate {
+    pub scan_id: u64,
+    pub branches: Vec<BranchStatus>,
+    pub removed_paths: Vec<String>,
+}
+
+/// The full working-tree status as of a single scan, keyed by repo-relative path.
+#[derive(Debug, Clone, Default)]
+pub struct ScanSnapshot {
+    pub scan_id: u64,
+    pub entries: HashMap<String, (String, FileStatus)>,
+}
+
+impl ScanSnapshot {
+    /// Diffs `self` against `previous`, returning only the branches whose entries actually
+    /// changed (added, moved to a different branch, or changed status) plus any path present in
+    /// `previous` but no longer dirty. Pure data comparison, no git access.
+    pub fn delta_since(&self, previous: &ScanSnapshot) -> (Vec<BranchStatus>, Vec<String>) {
+        let mut changed_by_branch: HashMap<String, Vec<StatusEntry>> = HashMap::new();
+        for (repo_path, (branch_id, status)) in &self.entries {
+            let unchanged = previous
+                .entries
+                .get(repo_path)
+                .is_some_and(|(prev_branch_id, prev_status)| {
+                    prev_branch_id == branch_id && prev_status == status
+                });
+            if !unchanged {
+                changed_by_branch
+                    .entry(branch_id.clone())
+                    .or_default()
+                    .push(StatusEntry {
+                        repo_path: repo_path.clone(),
+                        status: status.clone(),
+                    });
+            }
+        }
+
+        let removed_paths = previous
+            .entries
+            .keys()
+            .filter(|repo_path| !self.entries.contains_key(*repo_path))
+            .cloned()
+            .collect();
+
+        let branches = changed_by_branch
+            .into_iter()
+            .map(|(branch_id, entries)| BranchStatus { branch_id, entries })
+            .collect();
+
+        (branches, removed_paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(scan_id: u64, entries: &[(&str, &str, FileStatus)]) -> ScanSnapshot {
+        ScanSnapshot {
+            scan_id,
+            entries: entries
+                .iter()
+                .map(|(path, branch_id, status)| {
+                    (path.to_string(), (branch_id.to_string(), status.clone()))
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn first_scan_reports_every_entry_as_changed() {
+        let previous = ScanSnapshot::default();
+        let current = snapshot(1, &[("a.txt", "branch-1", FileStatus::Added)]);
+
+        let (branches, removed) = current.delta_since(&previous);
+
+        assert_eq!(branches.len(), 1);
+        assert_eq!(branches[0].branch_id, "branch-1");
+        assert_eq!(branches[0].entries, vec![StatusEntry {
+            repo_path: "a.txt".into(),
+            status: FileStatus::Added,
+        }]);
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn unchanged_entries_are_not_reported_again() {
+        let previous = snapshot(1, &[("a.txt", "branch-1", FileStatus::Modified)]);
+        let current = snapshot(2, &[("a.txt", "branch-1", FileStatus::Modified)]);
+
+        let (branches, removed) = current.delta_since(&previous);
+
+        assert!(branches.is_empty());
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn status_change_on_same_path_is_reported() {
+        let previous = snapshot(1, &[("a.txt", "branch-1", FileStatus::Modified)]);
+        let current = snapshot(2, &[("a.txt", "branch-1", FileStatus::Deleted)]);
+
+        let (branches, _removed) = current.delta_since(&previous);
+
+        assert_eq!(branches.len(), 1);
+        assert_eq!(branches[0].entries[0].status, FileStatus::Deleted);
+    }
+
+    #[test]
+    fn path_dropped_from_the_tree_is_reported_as_removed() {
+        let previous = snapshot(1, &[("a.txt", "branch-1", FileStatus::Modified)]);
+        let current = ScanSnapshot {
+            scan_id: 2,
+            entries: HashMap::new(),
+        };
+
+        let (branches, removed) = current.delta_since(&previous);
+
+        assert!(branches.is_empty());
+        assert_eq!(removed, vec!["a.txt".to_string()]);
+    }
+
+    #[test]
+    fn path_reassigned_to_a_different_branch_is_reported() {
+        let previous = snapshot(1, &[("a.txt", "branch-1", FileStatus::Modified)]);
+        let current = snapshot(2, &[("a.txt", "branch-2", FileStatus::Modified)]);
+
+        let (branches, _removed) = current.delta_since(&previous);
+
+        assert_eq!(branches.len(), 1);
+        assert_eq!(branches[0].branch_id, "branch-2");
+    }
+}